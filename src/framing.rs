@@ -0,0 +1,41 @@
+//! Length-prefixed message framing, for benchmarking servers that use a
+//! variable-length framed protocol instead of newline/fixed-size echo.
+//!
+//! The length prefix follows the FastCGI name-value-pair encoding: a single
+//! byte for values below 128, or a 4-byte big-endian value with the high
+//! bit of the first byte set for larger payloads.
+
+use std::io::{self, Read, Write};
+
+fn encode_length(len: u32, out: &mut Vec<u8>) {
+    if len < 128 {
+        out.push(len as u8);
+    } else {
+        out.extend_from_slice(&(len | 0x8000_0000).to_be_bytes());
+    }
+}
+
+/// Writes `body` preceded by its length prefix.
+pub fn write_frame<W: Write>(writer: &mut W, body: &[u8]) -> io::Result<()> {
+    let mut prefix = Vec::with_capacity(4);
+    encode_length(body.len() as u32, &mut prefix);
+    writer.write_all(&prefix)?;
+    writer.write_all(body)
+}
+
+/// Reads one length-prefixed frame into `buf`, resizing it to fit the body.
+/// `read_exact` blocks across as many underlying reads as it takes, so the
+/// prefix and body may each arrive split across multiple `read` calls.
+pub fn read_frame<R: Read>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first)?;
+    let len = if first[0] & 0x80 == 0 {
+        first[0] as u32
+    } else {
+        let mut rest = [0u8; 3];
+        reader.read_exact(&mut rest)?;
+        u32::from_be_bytes([first[0] & 0x7f, rest[0], rest[1], rest[2]])
+    };
+    buf.resize(len as usize, 0);
+    reader.read_exact(buf)
+}
@@ -0,0 +1,82 @@
+//! Lock-free-friendly latency histogram: each worker keeps its own, the
+//! final reduce step merges them with plain element-wise addition.
+//!
+//! Latencies (in nanoseconds) are mapped into logarithmic buckets with a
+//! fixed number of linear sub-buckets per power-of-two, so memory is
+//! constant and recording never allocates.
+
+/// Number of linear sub-buckets within each power-of-two range.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Covers the full range of a u64 nanosecond count.
+const NUM_BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+fn bucket_for(ns: u64) -> usize {
+    let ns = ns.max(1);
+    let msb = 63 - ns.leading_zeros();
+    if msb < SUB_BUCKET_BITS {
+        return ns as usize;
+    }
+    let exponent = msb - SUB_BUCKET_BITS;
+    let mantissa = (ns >> exponent) & (SUB_BUCKETS as u64 - 1);
+    (exponent + 1) as usize * SUB_BUCKETS + mantissa as usize
+}
+
+fn bucket_lower_bound(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let rel = bucket - SUB_BUCKETS;
+    let exponent = (rel / SUB_BUCKETS) as u32;
+    let mantissa = (rel % SUB_BUCKETS) as u64;
+    (SUB_BUCKETS as u64 + mantissa) << exponent
+}
+
+/// A per-thread latency histogram over nanosecond round-trip times.
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: vec![0; NUM_BUCKETS],
+            total: 0,
+        }
+    }
+
+    /// Records one observed round-trip latency. Never allocates.
+    pub fn record(&mut self, latency_ns: u64) {
+        self.counts[bucket_for(latency_ns)] += 1;
+        self.total += 1;
+    }
+
+    /// Merges `other` into `self` with element-wise addition.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *a += b;
+        }
+        self.total += other.total;
+    }
+
+    /// Approximate latency, in nanoseconds, at percentile `p` (0.0..=1.0).
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let target = ((self.total as f64) * p).ceil() as u64;
+        let mut cumulative = 0u64;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                return bucket_lower_bound(bucket);
+            }
+        }
+        bucket_lower_bound(NUM_BUCKETS - 1)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.percentile(1.0)
+    }
+}
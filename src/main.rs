@@ -1,17 +1,101 @@
+mod framing;
+mod histogram;
+mod reactor;
+mod server;
+
 use std::env;
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use histogram::Histogram;
+
+/// Wire framing used for each request/response round-trip.
+#[derive(Clone, Copy)]
+pub(crate) enum Frame {
+    /// Fixed `length`-byte messages, as before.
+    Fixed,
+    /// Each message is preceded by a FastCGI-style length prefix.
+    LengthPrefixed,
+}
+
+/// Attempts to re-establish the connection up to `retries` times, sleeping
+/// `interval` between attempts. Each failed attempt is counted in `errors`.
+/// Returns `None` once retries are exhausted.
+fn reconnect(
+    address: &str,
+    retries: u32,
+    interval: Duration,
+    errors: &AtomicU64,
+) -> Option<TcpStream> {
+    for _ in 0..retries {
+        thread::sleep(interval);
+        match TcpStream::connect(address) {
+            Ok(stream) => return Some(stream),
+            Err(_) => {
+                errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    None
+}
+
+/// Prints aggregate requests/sec and error count once a second until `stop`.
+pub(crate) fn report_throughput(counts: &[AtomicU64], errors: &[AtomicU64], stop: &AtomicBool) {
+    let mut last_count = 0u64;
+    let mut last_errors = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_secs(1));
+        let count: u64 = counts.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+        let errs: u64 = errors.iter().map(|e| e.load(Ordering::Relaxed)).sum();
+        println!(
+            "{} request/sec, {} errors/sec",
+            count - last_count,
+            errs - last_errors
+        );
+        last_count = count;
+        last_errors = errs;
+    }
+}
+
+/// Prints the final throughput/latency summary shared by every benchmark mode.
+pub(crate) fn print_summary(
+    address: &str,
+    number: u32,
+    length: usize,
+    duration: u64,
+    n_req: u64,
+    n_error: u64,
+    latencies: &Histogram,
+) {
+    println!(
+        "Benchmarking: {address}
+{number} clients, running {length} bytes, {duration} sec.
+
+Error: {n_error}
+Speed: {} request/sec
+
+Latency (p50/p90/p99/p99.9/max):
+  {:?} / {:?} / {:?} / {:?} / {:?}",
+        n_req / duration,
+        Duration::from_nanos(latencies.percentile(0.50)),
+        Duration::from_nanos(latencies.percentile(0.90)),
+        Duration::from_nanos(latencies.percentile(0.99)),
+        Duration::from_nanos(latencies.percentile(0.999)),
+        Duration::from_nanos(latencies.max()),
+    );
+}
 
 fn print_usage(program: &str, opts: &getopts::Options) {
     let brief = format!(
         r#"Echo benchmark.
 
 Usage:
-  {program} [ -a <address> ] [ -l <length> ] [ -c <number> ] [ -t <duration> ]
+  {program} [ -a <address> ] [ -l <length> ] [ -c <number> ] [ -t <duration> ] [ --frame <mode> ] [ --reactor ]
+  {program} --server [ -a <address> ]
   {program} (-h | --help)
   {program} --version"#,
         program = program
@@ -25,6 +109,11 @@ fn main() {
 
     let mut opts = getopts::Options::new();
     opts.optflag("h", "help", "Print this help.");
+    opts.optflag(
+        "s",
+        "server",
+        "Run as an echo server on <address> instead of benchmarking one.",
+    );
     opts.optopt(
         "a",
         "address",
@@ -49,11 +138,40 @@ fn main() {
         "Test connection number. Default: 50",
         "<number>",
     );
+    opts.optopt(
+        "",
+        "retries",
+        "Reconnect attempts after an I/O error before giving up. Default: 5",
+        "<retries>",
+    );
+    opts.optopt(
+        "",
+        "retry-interval",
+        "Milliseconds to wait between reconnect attempts. Default: 1000",
+        "<ms>",
+    );
+    opts.optopt(
+        "",
+        "rate",
+        "Cap each connection to this many requests/sec. Default: unlimited",
+        "<rate>",
+    );
+    opts.optopt(
+        "",
+        "frame",
+        "Wire framing: fixed or length-prefixed. Default: fixed",
+        "<mode>",
+    );
+    opts.optflag(
+        "",
+        "reactor",
+        "Drive connections with a small pool of epoll/mio event loops instead of one thread per connection.",
+    );
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(f) => {
-            eprintln!("{}", f.to_string());
+            eprintln!("{}", f);
             print_usage(&program, &opts);
             return;
         }
@@ -82,8 +200,36 @@ fn main() {
     let address = matches
         .opt_str("address")
         .unwrap_or_else(|| "127.0.0.1:8901".to_string());
+    let retries = matches
+        .opt_str("retries")
+        .unwrap_or_default()
+        .parse::<u32>()
+        .unwrap_or(5);
+    let retry_interval = Duration::from_millis(
+        matches
+            .opt_str("retry-interval")
+            .unwrap_or_default()
+            .parse::<u64>()
+            .unwrap_or(1000),
+    );
+    let rate = matches.opt_str("rate").and_then(|s| s.parse::<u32>().ok());
+    let request_interval = rate.map(|r| Duration::from_secs_f64(1.0 / r as f64));
+    let frame_mode = match matches.opt_str("frame").as_deref() {
+        Some("length-prefixed") => Frame::LengthPrefixed,
+        _ => Frame::Fixed,
+    };
+
+    if matches.opt_present("server") {
+        server::run(&address);
+        return;
+    }
 
     // max open file
+    let reserved_fds = if matches.opt_present("reactor") {
+        reactor::RESERVED_FDS
+    } else {
+        0
+    };
     let mut nofile_rlimit = libc::rlimit {
         rlim_cur: 0,
         rlim_max: 0,
@@ -92,44 +238,96 @@ fn main() {
         if 0 != libc::getrlimit(libc::RLIMIT_NOFILE, &mut nofile_rlimit) {
             panic!("getrlimit failed");
         }
-        if nofile_rlimit.rlim_max < number as u64 + 3 {
+        if nofile_rlimit.rlim_max < number as u64 + 3 + reserved_fds {
             panic!(
                 "the hard limit of this process is only {}",
                 nofile_rlimit.rlim_max
             )
         }
-        nofile_rlimit.rlim_cur = nofile_rlimit.rlim_max.min(number as u64 + 3);
+        nofile_rlimit.rlim_cur = nofile_rlimit.rlim_max.min(number as u64 + 3 + reserved_fds);
         if 0 != libc::setrlimit(libc::RLIMIT_NOFILE, &nofile_rlimit) {
             panic!("setrlimit failed");
         }
     }
 
+    if matches.opt_present("reactor") {
+        reactor::run(&address, number, duration, length, frame_mode);
+        return;
+    }
+
     let stop = Arc::new(AtomicBool::new(false));
     let control = Arc::downgrade(&stop);
 
+    let counts: Arc<Vec<AtomicU64>> = Arc::new((0..number).map(|_| AtomicU64::new(0)).collect());
+    let errors: Arc<Vec<AtomicU64>> = Arc::new((0..number).map(|_| AtomicU64::new(0)).collect());
+
+    let reporter = {
+        let stop = stop.clone();
+        let counts = counts.clone();
+        let errors = errors.clone();
+        thread::spawn(move || report_throughput(&counts, &errors, &stop))
+    };
+
     let group: Vec<_> = (0..number)
-        .map(|i| (i, address.clone(), stop.clone(), length))
-        .map(|(i, address, stop, length)| {
+        .map(|i| {
+            (
+                i,
+                address.clone(),
+                stop.clone(),
+                length,
+                counts.clone(),
+                errors.clone(),
+            )
+        })
+        .map(|(i, address, stop, length, counts, errors)| {
             thread::spawn(move || {
-                let mut count = 0;
                 let mut out_buf: Vec<u8> = vec![0; length];
                 out_buf[length - 1] = b'\n';
                 let mut in_buf: Vec<u8> = vec![0; length];
-                let mut stream = TcpStream::connect(address).unwrap();
+                let mut stream = TcpStream::connect(&address).unwrap();
+                let mut latencies = Histogram::new();
+                let mut next_deadline = Instant::now();
+                let errors = &errors[i as usize];
+                let count = &counts[i as usize];
 
                 while !stop.load(Ordering::Relaxed) {
-                    if let Err(e) = stream.write_all(&out_buf) {
-                        println!("thread {i} write error: {e}");
-                        return (count, 1);
-                    }
+                    let start = Instant::now();
 
-                    if let Err(e) = stream.read_exact(&mut in_buf) {
-                        println!("thread {i} read error: {e}");
-                        return (count, 1);
+                    let result = match frame_mode {
+                        Frame::Fixed => stream
+                            .write_all(&out_buf)
+                            .and_then(|_| stream.read_exact(&mut in_buf)),
+                        Frame::LengthPrefixed => framing::write_frame(&mut stream, &out_buf)
+                            .and_then(|_| framing::read_frame(&mut stream, &mut in_buf)),
                     };
-                    count += 1;
+
+                    match result {
+                        Ok(()) => {
+                            latencies.record(start.elapsed().as_nanos() as u64);
+                            count.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            println!("thread {i} connection error: {e}, reconnecting");
+                            errors.fetch_add(1, Ordering::Relaxed);
+                            match reconnect(&address, retries, retry_interval, errors) {
+                                Some(new_stream) => stream = new_stream,
+                                None => {
+                                    println!("thread {i} giving up after {retries} retries");
+                                    return latencies;
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(interval) = request_interval {
+                        next_deadline += interval;
+                        let now = Instant::now();
+                        if now < next_deadline {
+                            thread::sleep(next_deadline - now);
+                        }
+                    }
                 }
-                (count, 0)
+                latencies
             })
         })
         .collect();
@@ -137,19 +335,21 @@ fn main() {
     thread::sleep(Duration::from_secs(duration));
 
     control.upgrade().unwrap().store(true, Ordering::Relaxed);
+    reporter.join().unwrap();
 
-    let (n_req, n_error) = group
+    let latencies = group
         .into_iter()
         .map(|handle| handle.join().unwrap())
-        .reduce(|(a, b), (c, d)| (a + c, b + d))
+        .reduce(|mut hist_a, hist_c| {
+            hist_a.merge(&hist_c);
+            hist_a
+        })
         .unwrap();
 
-    println!(
-        "Benchmarking: {address}
-{number} clients, running {length} bytes, {duration} sec.
+    let n_req: u64 = counts.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+    let n_error: u64 = errors.iter().map(|e| e.load(Ordering::Relaxed)).sum();
 
-Error: {n_error}
-Speed: {} request/sec",
-        n_req / duration
+    print_summary(
+        &address, number, length, duration, n_req, n_error, &latencies,
     );
 }
@@ -0,0 +1,244 @@
+//! Optional `--reactor` mode: a small pool of nonblocking event loops drives
+//! all connections instead of one OS thread per connection, so connection
+//! count is no longer bounded by thread overhead. The fd-limit check still
+//! applies: each connection needs its own socket fd.
+//!
+//! Each reactor thread owns a shard of connections in a `mio::Poll` and
+//! advances every connection's write/read state machine as its socket
+//! becomes writable/readable. Reconnection and `--rate` pacing are left to
+//! the thread-per-connection path; this mode only targets raw connection
+//! scale.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream as StdTcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mio::net::TcpStream;
+use mio::{Events, Interest, Poll, Token};
+
+use crate::histogram::Histogram;
+use crate::{framing, print_summary, report_throughput, Frame};
+
+/// Number of event-loop threads sharding the connections.
+const REACTOR_THREADS: u32 = 4;
+
+/// Extra file descriptors reactor mode needs beyond one per connection: each
+/// reactor thread holds its own epoll fd.
+pub(crate) const RESERVED_FDS: u64 = REACTOR_THREADS as u64;
+
+enum Phase {
+    Writing,
+    Reading,
+}
+
+struct Connection {
+    stream: TcpStream,
+    wire: Vec<u8>,
+    pos: usize,
+    phase: Phase,
+    in_buf: Vec<u8>,
+    round_start: Instant,
+}
+
+impl Connection {
+    fn start_round(&mut self) {
+        self.pos = 0;
+        self.phase = Phase::Writing;
+        self.round_start = Instant::now();
+    }
+}
+
+/// Builds the literal bytes sent for every round-trip under `frame_mode`.
+/// The echo server mirrors whatever it receives, so reading back exactly
+/// this many bytes is always the correct round-trip length.
+fn build_wire(length: usize, frame_mode: Frame) -> Vec<u8> {
+    let mut payload: Vec<u8> = vec![0; length];
+    payload[length - 1] = b'\n';
+    match frame_mode {
+        Frame::Fixed => payload,
+        Frame::LengthPrefixed => {
+            let mut wire = Vec::with_capacity(length + 4);
+            framing::write_frame(&mut wire, &payload).unwrap();
+            wire
+        }
+    }
+}
+
+/// Advances `conn` until its socket would block or the connection ends.
+/// Returns `true` once the connection should be dropped.
+fn advance(
+    conn: &mut Connection,
+    count: &AtomicU64,
+    errors: &AtomicU64,
+    latencies: &mut Histogram,
+) -> bool {
+    loop {
+        let result = match conn.phase {
+            Phase::Writing => conn.stream.write(&conn.wire[conn.pos..]),
+            Phase::Reading => conn.stream.read(&mut conn.in_buf[conn.pos..]),
+        };
+        match result {
+            Ok(0) => return true,
+            Ok(n) => {
+                conn.pos += n;
+                match conn.phase {
+                    Phase::Writing if conn.pos == conn.wire.len() => {
+                        conn.phase = Phase::Reading;
+                        conn.pos = 0;
+                    }
+                    Phase::Reading if conn.pos == conn.in_buf.len() => {
+                        latencies.record(conn.round_start.elapsed().as_nanos() as u64);
+                        count.fetch_add(1, Ordering::Relaxed);
+                        conn.start_round();
+                    }
+                    _ => {}
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return false,
+            Err(_) => {
+                errors.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+        }
+    }
+}
+
+fn interest_for(phase: &Phase) -> Interest {
+    match phase {
+        Phase::Writing => Interest::WRITABLE,
+        Phase::Reading => Interest::READABLE,
+    }
+}
+
+fn run_reactor_thread(
+    address: String,
+    conn_count: u32,
+    wire: Vec<u8>,
+    stop: Arc<AtomicBool>,
+    count: &AtomicU64,
+    errors: &AtomicU64,
+) -> Histogram {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(1024);
+    let mut conns: HashMap<Token, Connection> = HashMap::new();
+    let mut latencies = Histogram::new();
+
+    for i in 0..conn_count {
+        let std_stream = StdTcpStream::connect(&address).unwrap();
+        std_stream.set_nonblocking(true).unwrap();
+        let mut stream = TcpStream::from_std(std_stream);
+        let token = Token(i as usize);
+        poll.registry()
+            .register(&mut stream, token, Interest::WRITABLE)
+            .unwrap();
+        conns.insert(
+            token,
+            Connection {
+                stream,
+                wire: wire.clone(),
+                pos: 0,
+                phase: Phase::Writing,
+                in_buf: vec![0; wire.len()],
+                round_start: Instant::now(),
+            },
+        );
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        poll.poll(&mut events, Some(Duration::from_millis(100)))
+            .unwrap();
+
+        let tokens: Vec<Token> = events.iter().map(|event| event.token()).collect();
+        for token in tokens {
+            let Some(conn) = conns.get_mut(&token) else {
+                continue;
+            };
+            if advance(conn, count, errors, &mut latencies) {
+                let mut conn = conns.remove(&token).unwrap();
+                let _ = poll.registry().deregister(&mut conn.stream);
+            } else {
+                poll.registry()
+                    .reregister(&mut conn.stream, token, interest_for(&conn.phase))
+                    .unwrap();
+            }
+        }
+    }
+
+    latencies
+}
+
+/// Runs the benchmark for `duration` seconds using the reactor mode.
+pub fn run(address: &str, number: u32, duration: u64, length: usize, frame_mode: Frame) {
+    let wire = build_wire(length, frame_mode);
+    let reactor_threads = REACTOR_THREADS.min(number).max(1);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let control = Arc::downgrade(&stop);
+
+    let counts: Arc<Vec<AtomicU64>> =
+        Arc::new((0..reactor_threads).map(|_| AtomicU64::new(0)).collect());
+    let errors: Arc<Vec<AtomicU64>> =
+        Arc::new((0..reactor_threads).map(|_| AtomicU64::new(0)).collect());
+
+    let reporter = {
+        let stop = stop.clone();
+        let counts = counts.clone();
+        let errors = errors.clone();
+        thread::spawn(move || report_throughput(&counts, &errors, &stop))
+    };
+
+    let base = number / reactor_threads;
+    let remainder = number % reactor_threads;
+
+    let group: Vec<_> = (0..reactor_threads)
+        .map(|i| {
+            let conn_count = base + u32::from(i < remainder);
+            (
+                i,
+                conn_count,
+                address.to_string(),
+                wire.clone(),
+                stop.clone(),
+                counts.clone(),
+                errors.clone(),
+            )
+        })
+        .map(|(i, conn_count, address, wire, stop, counts, errors)| {
+            thread::spawn(move || {
+                run_reactor_thread(
+                    address,
+                    conn_count,
+                    wire,
+                    stop,
+                    &counts[i as usize],
+                    &errors[i as usize],
+                )
+            })
+        })
+        .collect();
+
+    thread::sleep(Duration::from_secs(duration));
+
+    control.upgrade().unwrap().store(true, Ordering::Relaxed);
+    reporter.join().unwrap();
+
+    let latencies = group
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .reduce(|mut hist_a, hist_c| {
+            hist_a.merge(&hist_c);
+            hist_a
+        })
+        .unwrap();
+
+    let n_req: u64 = counts.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+    let n_error: u64 = errors.iter().map(|e| e.load(Ordering::Relaxed)).sum();
+
+    print_summary(
+        address, number, length, duration, n_req, n_error, &latencies,
+    );
+}
@@ -0,0 +1,42 @@
+//! Reference echo server so the benchmark is a single self-contained binary:
+//! `--server` binds `--address` and echoes back whatever bytes it receives,
+//! one thread per connection.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const BUF_SIZE: usize = 4096;
+
+fn handle_connection(mut stream: TcpStream) {
+    let mut buf = [0u8; BUF_SIZE];
+    loop {
+        let n = match stream.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => n,
+            Err(e) => {
+                println!("server connection read error: {e}");
+                return;
+            }
+        };
+        if let Err(e) = stream.write_all(&buf[..n]) {
+            println!("server connection write error: {e}");
+            return;
+        }
+    }
+}
+
+/// Runs the echo server, accepting connections on `address` forever.
+pub fn run(address: &str) {
+    let listener = TcpListener::bind(address).unwrap();
+    println!("Echo server listening on {address}");
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || handle_connection(stream));
+            }
+            Err(e) => println!("server accept error: {e}"),
+        }
+    }
+}